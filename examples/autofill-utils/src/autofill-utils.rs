@@ -7,41 +7,42 @@
 use anyhow::Result;
 use autofill::api::{addresses, credit_cards};
 use autofill::db::AutofillDb;
-use std::{fs::File, io::BufReader};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+};
 use structopt::StructOpt;
 use sync_guid::Guid;
 
+mod backup;
+mod cc_validate;
+mod import;
+mod search;
+mod vcard;
+
 // Note: this uses doc comments to generate the help text.
 #[derive(Clone, Debug, StructOpt)]
 #[structopt(name = "autofill-utils", about = "Command-line utilities for autofill")]
 pub struct Opts {
     /// Sets the path to the database
-    #[structopt(
-        name = "database_path",
-        long,
-        short = "d",
-        default_value = "./autofill.db"
-    )]
-    pub database_path: String,
+    #[structopt(name = "database_path", long, short = "d", conflicts_with = "in-memory")]
+    pub database_path: Option<String>,
 
     /// Disables all logging (useful for performance evaluation)
     #[structopt(name = "no-logging", long)]
     pub no_logging: bool,
 
+    /// Runs against an in-memory database instead of a file on disk
+    /// (cannot be combined with `-d`)
+    #[structopt(name = "in-memory", long)]
+    pub in_memory: bool,
+
     #[structopt(subcommand)]
     cmd: Command,
 }
 
 #[derive(Clone, Debug, StructOpt)]
 enum Command {
-    /// Adds JSON address
-    #[structopt(name = "add-address")]
-    AddAddress {
-        #[structopt(name = "input-file", long, short = "i")]
-        /// The input file containing the address to be added
-        input_file: String,
-    },
-
     /// Gets address from database
     #[structopt(name = "get-address")]
     GetAddress {
@@ -70,14 +71,22 @@ enum Command {
         guid: String,
     },
 
-    /// Adds JSON credit card
-    #[structopt(name = "add-credit-card")]
-    AddCreditCard {
+    /// Imports addresses from an RFC 6350 vCard (4.0) file
+    #[structopt(name = "import-addresses")]
+    ImportAddresses {
         #[structopt(name = "input-file", long, short = "i")]
-        /// The input file containing the credit card to be added
+        /// The vCard file containing the addresses to import
         input_file: String,
     },
 
+    /// Exports all addresses as an RFC 6350 vCard (4.0) file
+    #[structopt(name = "export-addresses")]
+    ExportAddresses {
+        #[structopt(name = "output-file", long, short = "o")]
+        /// The vCard file to write the exported addresses to
+        output_file: String,
+    },
+
     /// Gets credit card from database
     #[structopt(name = "get-credit-card")]
     GetCreditCard {
@@ -105,20 +114,60 @@ enum Command {
         /// The guid of the credit card to delete
         guid: String,
     },
-}
 
-fn run_add_address(db: &mut AutofillDb, filename: String) -> Result<()> {
-    println!("Retrieving address data from {}", filename);
+    /// Typo-tolerant search across addresses and credit cards
+    #[structopt(name = "search")]
+    Search {
+        /// The free-text query to search for
+        query: String,
 
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
-    let address_fields: addresses::NewAddressFields = serde_json::from_reader(reader)?;
+        #[structopt(name = "limit", long, short = "n", default_value = "10")]
+        /// The maximum number of results to print
+        limit: usize,
+    },
 
-    println!("Making `add_address` api call");
-    let address = addresses::add_address(&mut db.writer, address_fields)?;
+    /// Encrypts every address and credit card into a single backup file
+    #[structopt(name = "backup")]
+    Backup {
+        #[structopt(name = "output-file", long, short = "o")]
+        /// The file to write the encrypted backup to
+        output_file: String,
+    },
 
-    println!("Created address: {:#?}", address);
-    Ok(())
+    /// Restores addresses and credit cards from an encrypted backup file
+    #[structopt(name = "restore")]
+    Restore {
+        #[structopt(name = "input-file", long, short = "i")]
+        /// The encrypted backup file to restore from
+        input_file: String,
+
+        /// Deletes all existing addresses and credit cards before restoring
+        #[structopt(name = "replace", long)]
+        replace: bool,
+    },
+
+    /// Imports a batch of addresses or credit cards from a JSON array or
+    /// CSV file, merging with and deduplicating against existing rows
+    #[structopt(name = "import")]
+    Import {
+        #[structopt(name = "kind", long, short = "k", possible_values = &["addresses", "credit-cards"])]
+        /// The kind of record the input file contains
+        kind: String,
+
+        #[structopt(name = "input-file", long, short = "i")]
+        /// The JSON (array) or CSV file containing the records to import
+        input_file: String,
+
+        #[structopt(
+            name = "on-conflict",
+            long,
+            default_value = "insert",
+            possible_values = &["skip", "update", "insert"]
+        )]
+        /// What to do when an incoming record looks like a near-duplicate
+        /// of an existing one
+        on_conflict: String,
+    },
 }
 
 fn run_get_address(db: &mut AutofillDb, guid: String) -> Result<()> {
@@ -169,17 +218,34 @@ fn run_delete_address(db: &mut AutofillDb, guid: String) -> Result<()> {
     Ok(())
 }
 
-fn run_add_credit_card(db: &mut AutofillDb, filename: String) -> Result<()> {
-    println!("Retrieving credit card data from {}", filename);
+fn run_import_addresses(db: &mut AutofillDb, filename: String) -> Result<()> {
+    println!("Reading vCards from {}", filename);
 
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
-    let credit_card_fields: credit_cards::NewCreditCardFields = serde_json::from_reader(reader)?;
+    let mut file = File::open(filename)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
 
-    println!("Making `add_credit_card` api call");
-    let credit_card = credit_cards::add_credit_card(&mut db.writer, credit_card_fields)?;
+    let address_fields = vcard::parse_vcards(&contents)?;
+    println!("Parsed {} vCard(s)", address_fields.len());
 
-    println!("Created credit card: {:#?}", credit_card);
+    for fields in address_fields {
+        let address = addresses::add_address(&mut db.writer, fields)?;
+        println!("Created address: {:#?}", address);
+    }
+
+    Ok(())
+}
+
+fn run_export_addresses(db: &mut AutofillDb, filename: String) -> Result<()> {
+    println!("Exporting addresses to {}", filename);
+
+    let all_addresses = addresses::get_all_addresses(&mut db.writer)?;
+    let mut file = File::create(filename)?;
+    for address in &all_addresses {
+        file.write_all(vcard::encode_address(address).as_bytes())?;
+    }
+
+    println!("Exported {} address(es)", all_addresses.len());
     Ok(())
 }
 
@@ -207,9 +273,13 @@ fn run_update_credit_card(db: &mut AutofillDb, filename: String) -> Result<()> {
 
     let file = File::open(filename)?;
     let reader = BufReader::new(file);
-    let credit_card_fields: credit_cards::CreditCard = serde_json::from_reader(reader)?;
+    let mut credit_card_fields: credit_cards::CreditCard = serde_json::from_reader(reader)?;
     let guid = credit_card_fields.guid.clone();
 
+    let network = cc_validate::validate(&credit_card_fields.cc_number)?;
+    println!("Detected card network: {}", network);
+    credit_card_fields.cc_type = network.to_string();
+
     println!(
         "Making `update_credit_card` api call for guid {}",
         guid.to_string()
@@ -231,26 +301,149 @@ fn run_delete_credit_card(db: &mut AutofillDb, guid: String) -> Result<()> {
     Ok(())
 }
 
+fn run_search(db: &mut AutofillDb, query: String, limit: usize) -> Result<()> {
+    let all_addresses = addresses::get_all_addresses(&mut db.writer)?;
+    let all_credit_cards = credit_cards::get_all_credit_cards(&mut db.writer)?;
+
+    let results = search::search(&query, &all_addresses, &all_credit_cards);
+
+    println!("Found {} match(es) for `{}`:", results.len(), query);
+    for result in results.into_iter().take(limit) {
+        println!(
+            "  [{}] {} (matched {} token(s), edit distance {})",
+            result.kind, result.guid, result.matched_tokens, result.total_edit_distance
+        );
+    }
+
+    Ok(())
+}
+
+fn run_backup(db: &mut AutofillDb, filename: String) -> Result<()> {
+    let all_addresses = addresses::get_all_addresses(&mut db.writer)?;
+    let all_credit_cards = credit_cards::get_all_credit_cards(&mut db.writer)?;
+
+    let passphrase = rpassword::prompt_password("Backup passphrase: ")?;
+    let confirmation = rpassword::prompt_password("Confirm passphrase: ")?;
+    if passphrase != confirmation {
+        anyhow::bail!("passphrases did not match");
+    }
+
+    let encrypted = backup::encrypt(all_addresses, all_credit_cards, &passphrase)?;
+    let mut file = File::create(filename)?;
+    file.write_all(&encrypted)?;
+
+    println!("Wrote encrypted backup");
+    Ok(())
+}
+
+fn write_restored_records(
+    db: &mut AutofillDb,
+    replace: bool,
+    restored_addresses: &[addresses::Address],
+    restored_credit_cards: &[credit_cards::CreditCard],
+) -> Result<()> {
+    if replace {
+        for address in addresses::get_all_addresses(&mut db.writer)? {
+            addresses::delete_address(&mut db.writer, &address.guid)?;
+        }
+        for card in credit_cards::get_all_credit_cards(&mut db.writer)? {
+            credit_cards::delete_credit_card(&mut db.writer, &card.guid)?;
+        }
+    }
+
+    for address in restored_addresses {
+        addresses::add_address(&mut db.writer, backup::address_to_new_fields(address))?;
+    }
+    for card in restored_credit_cards {
+        credit_cards::add_credit_card(&mut db.writer, backup::credit_card_to_new_fields(card))?;
+    }
+    Ok(())
+}
+
+fn run_restore(db: &mut AutofillDb, filename: String, replace: bool) -> Result<()> {
+    let mut file = File::open(filename)?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+
+    let passphrase = rpassword::prompt_password("Backup passphrase: ")?;
+    let (restored_addresses, restored_credit_cards) = backup::decrypt(&data, &passphrase)?;
+
+    // Delete-then-reinsert happens inside one transaction so a failure
+    // partway through (bad data, constraint violation, I/O error) can't
+    // leave the store empty: either the whole restore lands, or none of
+    // it does and the prior data is left untouched.
+    db.writer.execute_batch("BEGIN")?;
+    if let Err(e) = write_restored_records(db, replace, &restored_addresses, &restored_credit_cards)
+    {
+        db.writer.execute_batch("ROLLBACK")?;
+        return Err(e);
+    }
+    db.writer.execute_batch("COMMIT")?;
+
+    println!(
+        "Restored {} address(es) and {} credit card(s)",
+        restored_addresses.len(),
+        restored_credit_cards.len()
+    );
+    Ok(())
+}
+
+fn run_import(
+    db: &mut AutofillDb,
+    kind: String,
+    input_file: String,
+    on_conflict: String,
+) -> Result<()> {
+    let on_conflict: import::OnConflict = on_conflict.parse()?;
+
+    let summary = match kind.as_str() {
+        "addresses" => import::run_import_addresses(db, &input_file, on_conflict)?,
+        "credit-cards" => import::run_import_credit_cards(db, &input_file, on_conflict)?,
+        other => anyhow::bail!("unknown --kind value `{}`", other),
+    };
+
+    println!(
+        "Imported {}: {} inserted, {} updated, {} skipped",
+        kind, summary.inserted, summary.updated, summary.skipped
+    );
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let opts = Opts::from_args();
     if !opts.no_logging {
         cli_support::init_trace_logging();
     }
 
-    let db_path = opts.database_path;
-    let mut db = AutofillDb::new(db_path)?;
+    let mut db = if opts.in_memory {
+        AutofillDb::new_memory()?
+    } else {
+        let db_path = opts.database_path.unwrap_or_else(|| "./autofill.db".to_string());
+        AutofillDb::new(db_path)?
+    };
 
     match opts.cmd {
-        Command::AddAddress { input_file } => run_add_address(&mut db, input_file),
         Command::GetAddress { guid } => run_get_address(&mut db, guid),
         Command::GetAllAddresses => run_get_all_addresses(&mut db),
         Command::UpdateAddress { input_file } => run_update_address(&mut db, input_file),
         Command::DeleteAddress { guid } => run_delete_address(&mut db, guid),
+        Command::ImportAddresses { input_file } => run_import_addresses(&mut db, input_file),
+        Command::ExportAddresses { output_file } => run_export_addresses(&mut db, output_file),
 
-        Command::AddCreditCard { input_file } => run_add_credit_card(&mut db, input_file),
         Command::GetCreditCard { guid } => run_get_credit_card(&mut db, guid),
         Command::GetAllCreditCards => run_get_all_credit_cards(&mut db),
         Command::UpdateCreditCard { input_file } => run_update_credit_card(&mut db, input_file),
         Command::DeleteCreditCard { guid } => run_delete_credit_card(&mut db, guid),
+        Command::Search { query, limit } => run_search(&mut db, query, limit),
+        Command::Backup { output_file } => run_backup(&mut db, output_file),
+        Command::Restore {
+            input_file,
+            replace,
+        } => run_restore(&mut db, input_file, replace),
+        Command::Import {
+            kind,
+            input_file,
+            on_conflict,
+        } => run_import(&mut db, kind, input_file, on_conflict),
     }
 }
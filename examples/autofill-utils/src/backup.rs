@@ -0,0 +1,220 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Encrypted whole-store backup/restore, so the autofill data in one
+//! database can be moved to another machine as a single passphrase
+//! protected file.
+
+use anyhow::{bail, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use autofill::api::{
+    addresses::{Address, NewAddressFields},
+    credit_cards::{CreditCard, NewCreditCardFields},
+};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+const MAGIC: &[u8; 4] = b"AFBK";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+const PARAMS_LEN: usize = 12; // m_cost, t_cost, p_cost, each a u32
+
+/// Argon2 cost parameters used for new backups. Stored in the header of
+/// every backup we write, so a future change here never breaks
+/// decrypting a backup written with the old values.
+const DEFAULT_M_COST: u32 = 19 * 1024; // 19 MiB, the OWASP-recommended minimum
+const DEFAULT_T_COST: u32 = 2;
+const DEFAULT_P_COST: u32 = 1;
+
+/// Sane bounds for KDF parameters read back out of a backup file. A
+/// corrupted or hostile header must never be able to push these past
+/// what `derive_key` is willing to run, e.g. an `m_cost` that tries to
+/// allocate hundreds of gigabytes before the auth tag is even checked.
+const MIN_M_COST: u32 = 8 * 1024; // 8 MiB
+const MAX_M_COST: u32 = 256 * 1024; // 256 MiB
+const MIN_T_COST: u32 = 1;
+const MAX_T_COST: u32 = 10;
+const MIN_P_COST: u32 = 1;
+const MAX_P_COST: u32 = 4;
+
+#[derive(Serialize, Deserialize)]
+struct BackupPayload {
+    addresses: Vec<Address>,
+    credit_cards: Vec<CreditCard>,
+}
+
+struct KdfParams {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl KdfParams {
+    fn to_bytes(&self) -> [u8; PARAMS_LEN] {
+        let mut bytes = [0u8; PARAMS_LEN];
+        bytes[0..4].copy_from_slice(&self.m_cost.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.t_cost.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.p_cost.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        KdfParams {
+            m_cost: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            t_cost: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            p_cost: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        }
+    }
+
+    /// Rejects KDF parameters outside of a sane range, so a corrupted
+    /// or hostile backup file can't force `derive_key` into an
+    /// expensive or memory-exhausting hash before the auth tag is
+    /// checked.
+    fn validate(&self) -> Result<()> {
+        if !(MIN_M_COST..=MAX_M_COST).contains(&self.m_cost) {
+            bail!(
+                "backup KDF m_cost {} is outside the allowed range {}..={}",
+                self.m_cost,
+                MIN_M_COST,
+                MAX_M_COST
+            );
+        }
+        if !(MIN_T_COST..=MAX_T_COST).contains(&self.t_cost) {
+            bail!(
+                "backup KDF t_cost {} is outside the allowed range {}..={}",
+                self.t_cost,
+                MIN_T_COST,
+                MAX_T_COST
+            );
+        }
+        if !(MIN_P_COST..=MAX_P_COST).contains(&self.p_cost) {
+            bail!(
+                "backup KDF p_cost {} is outside the allowed range {}..={}",
+                self.p_cost,
+                MIN_P_COST,
+                MAX_P_COST
+            );
+        }
+        Ok(())
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: &KdfParams) -> Result<[u8; KEY_LEN]> {
+    let argon2_params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(KEY_LEN))
+        .map_err(|e| anyhow::anyhow!("invalid KDF parameters: {}", e))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params);
+
+    let mut key = [0u8; KEY_LEN];
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("failed to derive key from passphrase: {}", e))?;
+    Ok(key)
+}
+
+/// Encrypts `addresses` and `credit_cards` into a versioned, header
+/// prefixed ciphertext:
+/// `MAGIC | version | kdf params | salt | nonce | ciphertext`.
+pub fn encrypt(addresses: Vec<Address>, credit_cards: Vec<CreditCard>, passphrase: &str) -> Result<Vec<u8>> {
+    let payload = BackupPayload {
+        addresses,
+        credit_cards,
+    };
+    let plaintext = serde_json::to_vec(&payload)?;
+
+    let kdf_params = KdfParams {
+        m_cost: DEFAULT_M_COST,
+        t_cost: DEFAULT_T_COST,
+        p_cost: DEFAULT_P_COST,
+    };
+
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let key_bytes = derive_key(passphrase, &salt, &kdf_params)?;
+
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key_bytes));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), plaintext.as_ref())
+        .map_err(|e| anyhow::anyhow!("encryption failed: {}", e))?;
+
+    let mut out = Vec::with_capacity(
+        MAGIC.len() + 1 + PARAMS_LEN + SALT_LEN + NONCE_LEN + ciphertext.len(),
+    );
+    out.extend_from_slice(MAGIC);
+    out.push(FORMAT_VERSION);
+    out.extend_from_slice(&kdf_params.to_bytes());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce_bytes);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Reverses [`encrypt`], validating the header and auth tag before
+/// returning the stored records.
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<(Vec<Address>, Vec<CreditCard>)> {
+    let params_start = MAGIC.len() + 1;
+    let salt_start = params_start + PARAMS_LEN;
+    let nonce_start = salt_start + SALT_LEN;
+    let header_len = nonce_start + NONCE_LEN;
+    if data.len() < header_len {
+        bail!("backup file is too short to contain a valid header");
+    }
+    if &data[0..MAGIC.len()] != MAGIC {
+        bail!("backup file does not start with the expected magic bytes");
+    }
+    let version = data[MAGIC.len()];
+    if version != FORMAT_VERSION {
+        bail!("unsupported backup format version {}", version);
+    }
+
+    let kdf_params = KdfParams::from_bytes(&data[params_start..salt_start]);
+    kdf_params.validate()?;
+    let salt = &data[salt_start..nonce_start];
+    let nonce_bytes = &data[nonce_start..header_len];
+    let ciphertext = &data[header_len..];
+
+    let key_bytes = derive_key(passphrase, salt, &kdf_params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key_bytes));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|_| anyhow::anyhow!("incorrect passphrase or corrupted backup file"))?;
+
+    let payload: BackupPayload = serde_json::from_slice(&plaintext)?;
+    Ok((payload.addresses, payload.credit_cards))
+}
+
+pub fn address_to_new_fields(address: &Address) -> NewAddressFields {
+    NewAddressFields {
+        given_name: address.given_name.clone(),
+        additional_name: address.additional_name.clone(),
+        family_name: address.family_name.clone(),
+        organization: address.organization.clone(),
+        street_address: address.street_address.clone(),
+        address_level3: address.address_level3.clone(),
+        address_level2: address.address_level2.clone(),
+        address_level1: address.address_level1.clone(),
+        postal_code: address.postal_code.clone(),
+        country: address.country.clone(),
+        tel: address.tel.clone(),
+        email: address.email.clone(),
+    }
+}
+
+pub fn credit_card_to_new_fields(card: &CreditCard) -> NewCreditCardFields {
+    NewCreditCardFields {
+        cc_name: card.cc_name.clone(),
+        cc_number: card.cc_number.clone(),
+        cc_exp_month: card.cc_exp_month,
+        cc_exp_year: card.cc_exp_year,
+        cc_type: card.cc_type.clone(),
+    }
+}
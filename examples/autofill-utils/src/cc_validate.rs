@@ -0,0 +1,160 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Card-number validation and network detection, run before a card is
+//! handed off to `autofill::api::credit_cards`.
+
+use anyhow::{bail, Result};
+
+/// Checks `number` against the Luhn checksum, ignoring any non-digit
+/// characters (spaces/dashes) a user might have typed in.
+pub fn luhn_is_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.is_empty() {
+        return false;
+    }
+
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| {
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum();
+
+    sum % 10 == 0
+}
+
+/// Derives the card network from its IIN (the leading digits), the way
+/// payment processors classify `card.brand`.
+pub fn detect_network(number: &str) -> Option<&'static str> {
+    let digits: String = number.chars().filter(|c| c.is_ascii_digit()).collect();
+
+    let prefix = |len: usize| digits.get(0..len).and_then(|s| s.parse::<u32>().ok());
+
+    if digits.starts_with('4') {
+        return Some("visa");
+    }
+    if let Some(p2) = prefix(2) {
+        if (51..=55).contains(&p2) {
+            return Some("mastercard");
+        }
+        if p2 == 34 || p2 == 37 {
+            return Some("amex");
+        }
+        if p2 == 65 {
+            return Some("discover");
+        }
+        if p2 == 35 {
+            return Some("jcb");
+        }
+        if [30, 36, 38].contains(&p2) {
+            return Some("diners");
+        }
+    }
+    if let Some(p4) = prefix(4) {
+        if (2221..=2720).contains(&p4) {
+            return Some("mastercard");
+        }
+        if digits.starts_with("6011") {
+            return Some("discover");
+        }
+    }
+    if let Some(p3) = prefix(3) {
+        if (644..=649).contains(&p3) {
+            return Some("discover");
+        }
+    }
+
+    None
+}
+
+/// Validates `number` and returns its detected network, or a clear
+/// error describing why the number was rejected.
+pub fn validate(number: &str) -> Result<&'static str> {
+    if !luhn_is_valid(number) {
+        bail!("card number `{}` failed Luhn validation", number);
+    }
+    match detect_network(number) {
+        Some(network) => Ok(network),
+        None => bail!("card number `{}` does not match a known card network", number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luhn_accepts_known_valid_numbers() {
+        assert!(luhn_is_valid("4111111111111111")); // visa test number
+        assert!(luhn_is_valid("5500005555555559")); // mastercard test number
+        assert!(luhn_is_valid("378282246310005")); // amex test number
+    }
+
+    #[test]
+    fn luhn_rejects_invalid_or_empty_numbers() {
+        assert!(!luhn_is_valid("4111111111111112"));
+        assert!(!luhn_is_valid(""));
+        assert!(!luhn_is_valid("abcd"));
+    }
+
+    #[test]
+    fn luhn_ignores_spaces_and_dashes() {
+        assert!(luhn_is_valid("4111-1111-1111-1111"));
+        assert!(luhn_is_valid("4111 1111 1111 1111"));
+    }
+
+    #[test]
+    fn detects_visa_by_leading_four() {
+        assert_eq!(detect_network("4111111111111111"), Some("visa"));
+    }
+
+    #[test]
+    fn detects_mastercard_at_iin_range_boundaries() {
+        assert_eq!(detect_network("5100000000000000"), Some("mastercard"));
+        assert_eq!(detect_network("5599000000000000"), Some("mastercard"));
+        assert_eq!(detect_network("2221000000000000"), Some("mastercard"));
+        assert_eq!(detect_network("2720000000000000"), Some("mastercard"));
+    }
+
+    #[test]
+    fn rejects_iin_just_outside_mastercard_range() {
+        assert_ne!(detect_network("2220000000000000"), Some("mastercard"));
+        assert_ne!(detect_network("2721000000000000"), Some("mastercard"));
+    }
+
+    #[test]
+    fn detects_amex_diners_jcb_discover() {
+        assert_eq!(detect_network("340000000000000"), Some("amex"));
+        assert_eq!(detect_network("370000000000000"), Some("amex"));
+        assert_eq!(detect_network("6011000000000000"), Some("discover"));
+        assert_eq!(detect_network("6440000000000000"), Some("discover"));
+        assert_eq!(detect_network("6500000000000000"), Some("discover"));
+        assert_eq!(detect_network("3500000000000000"), Some("jcb"));
+        assert_eq!(detect_network("3000000000000000"), Some("diners"));
+    }
+
+    #[test]
+    fn validate_surfaces_an_error_for_bad_luhn() {
+        assert!(validate("4111111111111112").is_err());
+    }
+
+    #[test]
+    fn validate_surfaces_an_error_for_unknown_network() {
+        // Passes Luhn but doesn't match any known IIN prefix.
+        assert!(luhn_is_valid("9999000000000004"));
+        assert!(validate("9999000000000004").is_err());
+    }
+}
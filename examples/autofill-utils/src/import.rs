@@ -0,0 +1,313 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Batch import of addresses and credit cards from a JSON array or CSV
+//! file, deduplicating against rows already in the store.
+
+use crate::cc_validate;
+use anyhow::{bail, Result};
+use autofill::api::{
+    addresses::{self, Address, NewAddressFields},
+    credit_cards::{self, CreditCard, NewCreditCardFields},
+};
+use autofill::db::AutofillDb;
+use std::{fs::File, path::Path};
+
+#[derive(Clone, Copy, Debug)]
+pub enum OnConflict {
+    Skip,
+    Update,
+    Insert,
+}
+
+impl std::str::FromStr for OnConflict {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(OnConflict::Skip),
+            "update" => Ok(OnConflict::Update),
+            "insert" => Ok(OnConflict::Insert),
+            other => bail!("unknown --on-conflict value `{}`", other),
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+fn is_csv(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("csv"))
+        .unwrap_or(false)
+}
+
+fn read_records<T: for<'de> serde::Deserialize<'de>>(filename: &str) -> Result<Vec<T>> {
+    if is_csv(filename) {
+        let mut reader = csv::Reader::from_path(filename)?;
+        let mut records = Vec::new();
+        for result in reader.deserialize() {
+            records.push(result?);
+        }
+        Ok(records)
+    } else {
+        let file = File::open(filename)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+fn normalize(value: &str) -> String {
+    value.trim().to_lowercase()
+}
+
+/// True if two addresses are near-duplicates: same normalized street,
+/// postal code, and name, ignoring case and surrounding whitespace.
+fn addresses_match(
+    a_street: &str,
+    a_postal: &str,
+    a_given: &str,
+    a_family: &str,
+    b_street: &str,
+    b_postal: &str,
+    b_given: &str,
+    b_family: &str,
+) -> bool {
+    normalize(a_street) == normalize(b_street)
+        && normalize(a_postal) == normalize(b_postal)
+        && normalize(a_given) == normalize(b_given)
+        && normalize(a_family) == normalize(b_family)
+}
+
+/// True if two credit cards are near-duplicates: same last four
+/// digits, expiry, and name (ignoring case/whitespace on the name).
+fn credit_cards_match(
+    a_last_four: &str,
+    a_exp_month: i64,
+    a_exp_year: i64,
+    a_name: &str,
+    b_last_four: &str,
+    b_exp_month: i64,
+    b_exp_year: i64,
+    b_name: &str,
+) -> bool {
+    a_last_four == b_last_four
+        && a_exp_month == b_exp_month
+        && a_exp_year == b_exp_year
+        && normalize(a_name) == normalize(b_name)
+}
+
+/// Returns the index of the first near-duplicate of `candidate` in
+/// `existing`. Indexed rather than borrowed so callers can keep
+/// mutating `existing` (e.g. to track records inserted/updated earlier
+/// in the same batch) without fighting the borrow checker.
+fn find_duplicate_address(existing: &[Address], candidate: &NewAddressFields) -> Option<usize> {
+    existing.iter().position(|address| {
+        addresses_match(
+            &address.street_address,
+            &address.postal_code,
+            &address.given_name,
+            &address.family_name,
+            &candidate.street_address,
+            &candidate.postal_code,
+            &candidate.given_name,
+            &candidate.family_name,
+        )
+    })
+}
+
+fn find_duplicate_credit_card(
+    existing: &[CreditCard],
+    candidate: &NewCreditCardFields,
+) -> Option<usize> {
+    existing.iter().position(|card| {
+        credit_cards_match(
+            &card.cc_number_last_four,
+            card.cc_exp_month,
+            card.cc_exp_year,
+            &card.cc_name,
+            &candidate.cc_number_last_four,
+            candidate.cc_exp_month,
+            candidate.cc_exp_year,
+            &candidate.cc_name,
+        )
+    })
+}
+
+/// Imports `input_file` as addresses, merging with and deduplicating
+/// against existing rows per `on_conflict`. All writes happen in a
+/// single transaction, so a malformed row rolls back the whole batch.
+pub fn run_import_addresses(
+    db: &mut AutofillDb,
+    input_file: &str,
+    on_conflict: OnConflict,
+) -> Result<ImportSummary> {
+    let incoming: Vec<NewAddressFields> = read_records(input_file)?;
+    let mut existing = addresses::get_all_addresses(&mut db.writer)?;
+    let mut summary = ImportSummary::default();
+
+    db.writer.execute_batch("BEGIN")?;
+    for candidate in incoming {
+        let result = (|| -> Result<()> {
+            match find_duplicate_address(&existing, &candidate) {
+                None => {
+                    let address = addresses::add_address(&mut db.writer, candidate.clone())?;
+                    existing.push(address);
+                    summary.inserted += 1;
+                }
+                Some(duplicate_idx) => match on_conflict {
+                    OnConflict::Skip => summary.skipped += 1,
+                    OnConflict::Insert => {
+                        let address = addresses::add_address(&mut db.writer, candidate.clone())?;
+                        existing.push(address);
+                        summary.inserted += 1;
+                    }
+                    OnConflict::Update => {
+                        let mut updated = existing[duplicate_idx].clone();
+                        updated.given_name = candidate.given_name.clone();
+                        updated.additional_name = candidate.additional_name.clone();
+                        updated.family_name = candidate.family_name.clone();
+                        updated.organization = candidate.organization.clone();
+                        updated.street_address = candidate.street_address.clone();
+                        updated.address_level3 = candidate.address_level3.clone();
+                        updated.address_level2 = candidate.address_level2.clone();
+                        updated.address_level1 = candidate.address_level1.clone();
+                        updated.postal_code = candidate.postal_code.clone();
+                        updated.country = candidate.country.clone();
+                        updated.tel = candidate.tel.clone();
+                        updated.email = candidate.email.clone();
+                        addresses::update_address(&mut db.writer, updated.clone())?;
+                        existing[duplicate_idx] = updated;
+                        summary.updated += 1;
+                    }
+                },
+            }
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            db.writer.execute_batch("ROLLBACK")?;
+            return Err(e);
+        }
+    }
+    db.writer.execute_batch("COMMIT")?;
+
+    Ok(summary)
+}
+
+/// Imports `input_file` as credit cards, validating and deduplicating
+/// the same way [`run_import_addresses`] does for addresses.
+pub fn run_import_credit_cards(
+    db: &mut AutofillDb,
+    input_file: &str,
+    on_conflict: OnConflict,
+) -> Result<ImportSummary> {
+    let incoming: Vec<NewCreditCardFields> = read_records(input_file)?;
+    let mut existing = credit_cards::get_all_credit_cards(&mut db.writer)?;
+    let mut summary = ImportSummary::default();
+
+    db.writer.execute_batch("BEGIN")?;
+    for mut candidate in incoming {
+        let result = (|| -> Result<()> {
+            let network = cc_validate::validate(&candidate.cc_number)?;
+            candidate.cc_type = network.to_string();
+
+            match find_duplicate_credit_card(&existing, &candidate) {
+                None => {
+                    let card = credit_cards::add_credit_card(&mut db.writer, candidate.clone())?;
+                    existing.push(card);
+                    summary.inserted += 1;
+                }
+                Some(duplicate_idx) => match on_conflict {
+                    OnConflict::Skip => summary.skipped += 1,
+                    OnConflict::Insert => {
+                        let card = credit_cards::add_credit_card(&mut db.writer, candidate.clone())?;
+                        existing.push(card);
+                        summary.inserted += 1;
+                    }
+                    OnConflict::Update => {
+                        let mut updated = existing[duplicate_idx].clone();
+                        updated.cc_name = candidate.cc_name.clone();
+                        updated.cc_number = candidate.cc_number.clone();
+                        updated.cc_exp_month = candidate.cc_exp_month;
+                        updated.cc_exp_year = candidate.cc_exp_year;
+                        updated.cc_type = candidate.cc_type.clone();
+                        credit_cards::update_credit_card(&mut db.writer, updated.clone())?;
+                        existing[duplicate_idx] = updated;
+                        summary.updated += 1;
+                    }
+                },
+            }
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            db.writer.execute_batch("ROLLBACK")?;
+            return Err(e);
+        }
+    }
+    db.writer.execute_batch("COMMIT")?;
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_match_ignores_case_and_whitespace() {
+        assert!(addresses_match(
+            "123 Main St", "62704", "Jane", "Doe", " 123 MAIN ST ", "62704", "jane", "DOE",
+        ));
+    }
+
+    #[test]
+    fn addresses_match_requires_every_field_to_match() {
+        assert!(!addresses_match(
+            "123 Main St", "62704", "Jane", "Doe", "123 Main St", "62704", "Jane", "Smith",
+        ));
+        assert!(!addresses_match(
+            "123 Main St", "62704", "Jane", "Doe", "456 Oak Ave", "62704", "Jane", "Doe",
+        ));
+    }
+
+    #[test]
+    fn credit_cards_match_on_last_four_expiry_and_name() {
+        assert!(credit_cards_match(
+            "1234", 4, 2030, "Jane Doe", "1234", 4, 2030, " jane doe ",
+        ));
+    }
+
+    #[test]
+    fn credit_cards_match_rejects_different_expiry_or_last_four() {
+        assert!(!credit_cards_match(
+            "1234", 4, 2030, "Jane Doe", "1234", 5, 2030, "Jane Doe",
+        ));
+        assert!(!credit_cards_match(
+            "1234", 4, 2030, "Jane Doe", "5678", 4, 2030, "Jane Doe",
+        ));
+    }
+
+    #[test]
+    fn is_csv_checks_the_file_extension() {
+        assert!(is_csv("cards.csv"));
+        assert!(is_csv("CARDS.CSV"));
+        assert!(!is_csv("cards.json"));
+        assert!(!is_csv("cards"));
+    }
+
+    #[test]
+    fn on_conflict_parses_known_values_and_rejects_others() {
+        assert!(matches!("skip".parse::<OnConflict>().unwrap(), OnConflict::Skip));
+        assert!(matches!("update".parse::<OnConflict>().unwrap(), OnConflict::Update));
+        assert!(matches!("insert".parse::<OnConflict>().unwrap(), OnConflict::Insert));
+        assert!("bogus".parse::<OnConflict>().is_err());
+    }
+}
@@ -0,0 +1,268 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Typo-tolerant ranking over addresses and credit cards, so a record
+//! can be found without knowing its exact spelling or guid.
+
+use autofill::api::{addresses::Address, credit_cards::CreditCard};
+
+/// One field pulled off a record to match tokens against, in priority
+/// order (lower `priority` wins ties).
+struct IndexedField<'a> {
+    value: &'a str,
+    priority: u8,
+}
+
+pub struct SearchResult {
+    pub guid: String,
+    pub kind: &'static str,
+    pub matched_tokens: usize,
+    pub total_edit_distance: usize,
+    pub best_priority: u8,
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|t| t.to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Max edits allowed for a token, scaled by its length.
+fn edit_budget(token_len: usize) -> usize {
+    if (4..=7).contains(&token_len) {
+        1
+    } else if token_len > 7 {
+        2
+    } else {
+        0
+    }
+}
+
+/// Bounded Levenshtein distance: returns `None` once the edit distance
+/// is certain to exceed `max_edits`, so callers can skip far-off fields
+/// cheaply.
+fn bounded_edit_distance(a: &str, b: &str, max_edits: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    if a.len().abs_diff(b.len()) > max_edits {
+        return None;
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut cur = vec![0usize; b.len() + 1];
+        cur[0] = i + 1;
+        let mut row_min = cur[0];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+            row_min = row_min.min(cur[j + 1]);
+        }
+        if row_min > max_edits {
+            return None;
+        }
+        prev = cur;
+    }
+
+    let distance = prev[b.len()];
+    if distance > max_edits {
+        None
+    } else {
+        Some(distance)
+    }
+}
+
+/// Best (edit distance, priority) match of `token` against any word in
+/// `field.value`, or `None` if nothing is within budget.
+fn match_field(token: &str, field: &IndexedField<'_>) -> Option<(usize, u8)> {
+    let budget = edit_budget(token.len());
+    let mut best: Option<(usize, u8)> = None;
+
+    for word in tokenize(field.value) {
+        let is_prefix = word.starts_with(token);
+        let distance = if is_prefix {
+            Some(0)
+        } else {
+            bounded_edit_distance(token, &word, budget)
+        };
+        if let Some(distance) = distance {
+            // An exact prefix match always outranks a mid-word match at
+            // the same field, modeled as a lower effective priority.
+            let priority = if is_prefix {
+                field.priority
+            } else {
+                field.priority + 1
+            };
+            best = Some(match best {
+                Some((best_distance, best_priority))
+                    if (best_distance, best_priority) <= (distance, priority) =>
+                {
+                    (best_distance, best_priority)
+                }
+                _ => (distance, priority),
+            });
+        }
+    }
+
+    best
+}
+
+fn score(tokens: &[String], fields: &[IndexedField<'_>]) -> (usize, usize, u8) {
+    let mut matched_tokens = 0;
+    let mut total_edit_distance = 0;
+    let mut best_priority = u8::MAX;
+
+    for token in tokens {
+        let mut best_for_token: Option<(usize, u8)> = None;
+        for field in fields {
+            if let Some((distance, priority)) = match_field(token, field) {
+                best_for_token = Some(match best_for_token {
+                    Some((best_distance, best_p)) if (best_distance, best_p) <= (distance, priority) => {
+                        (best_distance, best_p)
+                    }
+                    _ => (distance, priority),
+                });
+            }
+        }
+        if let Some((distance, priority)) = best_for_token {
+            matched_tokens += 1;
+            total_edit_distance += distance;
+            best_priority = best_priority.min(priority);
+        }
+    }
+
+    (matched_tokens, total_edit_distance, best_priority)
+}
+
+fn address_fields(address: &Address) -> Vec<IndexedField<'_>> {
+    vec![
+        IndexedField { value: &address.given_name, priority: 0 },
+        IndexedField { value: &address.family_name, priority: 0 },
+        IndexedField { value: &address.street_address, priority: 1 },
+        IndexedField { value: &address.address_level2, priority: 1 },
+        IndexedField { value: &address.postal_code, priority: 1 },
+    ]
+}
+
+fn credit_card_fields(card: &CreditCard) -> Vec<IndexedField<'_>> {
+    vec![IndexedField { value: &card.cc_name, priority: 0 }]
+}
+
+/// Ranks every address and credit card against `query`, returning the
+/// matches sorted best-first.
+pub fn search(query: &str, addresses: &[Address], credit_cards: &[CreditCard]) -> Vec<SearchResult> {
+    let tokens = tokenize(query);
+    let mut results = Vec::new();
+
+    for address in addresses {
+        let fields = address_fields(address);
+        let (matched_tokens, total_edit_distance, best_priority) = score(&tokens, &fields);
+        if matched_tokens > 0 {
+            results.push(SearchResult {
+                guid: address.guid.to_string(),
+                kind: "address",
+                matched_tokens,
+                total_edit_distance,
+                best_priority,
+            });
+        }
+    }
+
+    for card in credit_cards {
+        let fields = credit_card_fields(card);
+        let (matched_tokens, total_edit_distance, best_priority) = score(&tokens, &fields);
+        if matched_tokens > 0 {
+            results.push(SearchResult {
+                guid: card.guid.to_string(),
+                kind: "credit card",
+                matched_tokens,
+                total_edit_distance,
+                best_priority,
+            });
+        }
+    }
+
+    results.sort_by(|a, b| {
+        b.matched_tokens
+            .cmp(&a.matched_tokens)
+            .then(a.total_edit_distance.cmp(&b.total_edit_distance))
+            .then(a.best_priority.cmp(&b.best_priority))
+    });
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_budget_scales_with_token_length() {
+        assert_eq!(edit_budget(3), 0);
+        assert_eq!(edit_budget(4), 1);
+        assert_eq!(edit_budget(7), 1);
+        assert_eq!(edit_budget(8), 2);
+    }
+
+    #[test]
+    fn bounded_edit_distance_matches_plain_levenshtein_within_budget() {
+        assert_eq!(bounded_edit_distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(bounded_edit_distance("same", "same", 0), Some(0));
+        assert_eq!(bounded_edit_distance("", "abc", 3), Some(3));
+    }
+
+    #[test]
+    fn bounded_edit_distance_exits_early_past_budget() {
+        assert_eq!(bounded_edit_distance("kitten", "sitting", 1), None);
+        assert_eq!(bounded_edit_distance("abcdef", "uvwxyz", 2), None);
+    }
+
+    #[test]
+    fn match_field_prefers_prefix_match_over_mid_word_typo() {
+        let field = IndexedField {
+            value: "springfield",
+            priority: 1,
+        };
+        let (distance, priority) = match_field("spring", &field).unwrap();
+        assert_eq!((distance, priority), (0, 1));
+    }
+
+    #[test]
+    fn match_field_falls_back_to_bounded_edit_distance() {
+        let field = IndexedField {
+            value: "springfield",
+            priority: 1,
+        };
+        // "springfeld" is one deletion away from "springfield".
+        let (distance, priority) = match_field("springfeld", &field).unwrap();
+        assert_eq!(distance, 1);
+        assert_eq!(priority, 2); // mid-word match, one worse than the field's own priority
+    }
+
+    #[test]
+    fn match_field_returns_none_outside_budget() {
+        let field = IndexedField {
+            value: "springfield",
+            priority: 0,
+        };
+        assert!(match_field("zzzzzzzzzz", &field).is_none());
+    }
+
+    #[test]
+    fn score_ranks_more_matched_tokens_above_fewer() {
+        let fields = vec![
+            IndexedField { value: "jane", priority: 0 },
+            IndexedField { value: "doe", priority: 0 },
+        ];
+        let one_token = vec!["jane".to_string()];
+        let two_tokens = vec!["jane".to_string(), "doe".to_string()];
+
+        let (matched_one, _, _) = score(&one_token, &fields);
+        let (matched_two, _, _) = score(&two_tokens, &fields);
+        assert_eq!(matched_one, 1);
+        assert_eq!(matched_two, 2);
+    }
+}
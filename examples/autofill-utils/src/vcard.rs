@@ -0,0 +1,294 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Minimal RFC 6350 (vCard 4.0) encoding/decoding for addresses: the
+//! name (`FN`), postal address (`ADR`), phone (`TEL`) and email
+//! (`EMAIL`). `organization` and `address_level3` are not written on
+//! export or read on import.
+
+use anyhow::{bail, Result};
+use autofill::api::addresses::{Address, NewAddressFields};
+
+/// Joins the name-ish fields into a single `FN` value.
+fn format_name(given_name: &str, additional_name: &str, family_name: &str) -> String {
+    [given_name, additional_name, family_name]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(',') => out.push(','),
+                Some(';') => out.push(';'),
+                Some('n') | Some('N') => out.push('\n'),
+                Some('r') | Some('R') => out.push('\r'),
+                Some('\\') => out.push('\\'),
+                Some(other) => out.push(other),
+                None => {}
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Splits `value` on semicolons that aren't escaped with a backslash.
+fn split_unescaped_semicolons(value: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            current.push(c);
+            if let Some(next) = chars.next() {
+                current.push(next);
+            }
+        } else if c == ';' {
+            fields.push(current.clone());
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    fields.push(current);
+    fields
+}
+
+/// Encodes a single address as one `BEGIN:VCARD`/`END:VCARD` block.
+pub fn encode_address(address: &Address) -> String {
+    let fn_value = format_name(
+        &address.given_name,
+        &address.additional_name,
+        &address.family_name,
+    );
+    let adr_value = [
+        "", // post-office-box
+        "", // extended
+        &escape(&address.street_address),
+        &escape(&address.address_level2),
+        &escape(&address.address_level1),
+        &escape(&address.postal_code),
+        &escape(&address.country),
+    ]
+    .join(";");
+
+    let mut card = String::new();
+    card.push_str("BEGIN:VCARD\r\n");
+    card.push_str("VERSION:4.0\r\n");
+    card.push_str(&format!("FN:{}\r\n", escape(&fn_value)));
+    card.push_str(&format!("ADR:{}\r\n", adr_value));
+    if !address.tel.is_empty() {
+        card.push_str(&format!("TEL:{}\r\n", escape(&address.tel)));
+    }
+    if !address.email.is_empty() {
+        card.push_str(&format!("EMAIL:{}\r\n", escape(&address.email)));
+    }
+    card.push_str("END:VCARD\r\n");
+    card
+}
+
+/// Unfolds continuation lines (lines beginning with a space or tab
+/// continue the previous physical line), per RFC 6350 section 3.2.
+fn unfold(input: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw_line in input.split("\r\n").flat_map(|l| l.split('\n')) {
+        let line = raw_line.trim_end_matches('\r');
+        if let Some(first) = line.chars().next() {
+            if (first == ' ' || first == '\t') && !lines.is_empty() {
+                let last = lines.last_mut().unwrap();
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines
+}
+
+/// Parses every `VCARD` block in `input` into `NewAddressFields`, ready
+/// to be passed to `add_address`.
+pub fn parse_vcards(input: &str) -> Result<Vec<NewAddressFields>> {
+    let mut addresses = Vec::new();
+    let mut current: Option<NewAddressFields> = None;
+
+    for line in unfold(input) {
+        if line.eq_ignore_ascii_case("BEGIN:VCARD") {
+            current = Some(NewAddressFields::default());
+            continue;
+        }
+        if line.eq_ignore_ascii_case("END:VCARD") {
+            match current.take() {
+                Some(fields) => addresses.push(fields),
+                None => bail!("END:VCARD without matching BEGIN:VCARD"),
+            }
+            continue;
+        }
+        let fields = match current.as_mut() {
+            Some(fields) => fields,
+            None => continue,
+        };
+
+        let (name, value) = match line.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        // Strip any `;TYPE=...`-style parameters from the property name.
+        let property = name.split(';').next().unwrap_or(name).to_ascii_uppercase();
+
+        match property.as_str() {
+            "FN" => {
+                // Inverse of `format_name`: first word is the given name,
+                // last word is the family name, anything in between is
+                // the additional name, so a round trip through
+                // export/import doesn't drop a middle name.
+                let unescaped = unescape(value);
+                let parts: Vec<&str> = unescaped.split(' ').filter(|p| !p.is_empty()).collect();
+                match parts.len() {
+                    0 => {
+                        fields.given_name = String::new();
+                        fields.additional_name = String::new();
+                        fields.family_name = String::new();
+                    }
+                    1 => {
+                        fields.given_name = parts[0].to_string();
+                        fields.additional_name = String::new();
+                        fields.family_name = String::new();
+                    }
+                    _ => {
+                        fields.given_name = parts[0].to_string();
+                        fields.family_name = parts[parts.len() - 1].to_string();
+                        fields.additional_name = parts[1..parts.len() - 1].join(" ");
+                    }
+                }
+            }
+            "ADR" => {
+                let components = split_unescaped_semicolons(value);
+                let get = |idx: usize| components.get(idx).map(|v| unescape(v)).unwrap_or_default();
+                fields.street_address = get(2);
+                fields.address_level2 = get(3);
+                fields.address_level1 = get(4);
+                fields.postal_code = get(5);
+                fields.country = get(6);
+            }
+            "TEL" => fields.tel = unescape(value),
+            "EMAIL" => fields.email = unescape(value),
+            _ => {}
+        }
+    }
+
+    Ok(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_then_unescape_round_trips() {
+        let value = "Jane, Q.; \"Doe\"\\Esq.";
+        assert_eq!(unescape(&escape(value)), value);
+    }
+
+    #[test]
+    fn escape_then_unescape_round_trips_embedded_newlines() {
+        let value = "123 Main St\r\nApt 4";
+        assert_eq!(unescape(&escape(value)), value);
+        // The escaped form must not contain a bare CR or LF, or `unfold`
+        // would treat the second physical line as a new property.
+        assert!(!escape(value).contains('\n'));
+        assert!(!escape(value).contains('\r'));
+    }
+
+    #[test]
+    fn unescape_handles_escaped_newline() {
+        assert_eq!(unescape("line1\\nline2"), "line1\nline2");
+    }
+
+    #[test]
+    fn split_unescaped_semicolons_respects_escapes() {
+        let fields = split_unescaped_semicolons("a;b\\;still-b;c");
+        assert_eq!(fields, vec!["a", "b\\;still-b", "c"]);
+    }
+
+    #[test]
+    fn split_unescaped_semicolons_on_empty_components() {
+        let fields = split_unescaped_semicolons(";;street;;;postal;country");
+        assert_eq!(
+            fields,
+            vec!["", "", "street", "", "", "postal", "country"]
+        );
+    }
+
+    #[test]
+    fn parse_vcards_unfolds_continuation_lines() {
+        // Unfolding drops exactly one leading space, so the second one
+        // is the space between the two names.
+        let input = "BEGIN:VCARD\r\nFN:Jane\r\n  Doe\r\nEND:VCARD\r\n";
+        let parsed = parse_vcards(input).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].given_name, "Jane");
+        assert_eq!(parsed[0].family_name, "Doe");
+    }
+
+    #[test]
+    fn parse_vcards_round_trips_a_middle_name() {
+        let fn_value = format_name("Jane", "Q", "Doe");
+        assert_eq!(fn_value, "Jane Q Doe");
+
+        let input = format!("BEGIN:VCARD\r\nFN:{}\r\nEND:VCARD\r\n", fn_value);
+        let parsed = parse_vcards(&input).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].given_name, "Jane");
+        assert_eq!(parsed[0].additional_name, "Q");
+        assert_eq!(parsed[0].family_name, "Doe");
+    }
+
+    #[test]
+    fn parse_vcards_splits_adr_into_components() {
+        let input = "BEGIN:VCARD\r\nFN:Jane Doe\r\nADR:;;123 Main St;Springfield;IL;62704;US\r\nEND:VCARD\r\n";
+        let parsed = parse_vcards(input).unwrap();
+        assert_eq!(parsed[0].street_address, "123 Main St");
+        assert_eq!(parsed[0].address_level2, "Springfield");
+        assert_eq!(parsed[0].address_level1, "IL");
+        assert_eq!(parsed[0].postal_code, "62704");
+        assert_eq!(parsed[0].country, "US");
+    }
+
+    #[test]
+    fn parse_vcards_round_trips_a_multi_line_street_address() {
+        let street = "123 Main St\r\nApt 4";
+        let adr_value = format!(";;{};Springfield;IL;62704;US", escape(street));
+        let input = format!(
+            "BEGIN:VCARD\r\nFN:Jane Doe\r\nADR:{}\r\nEND:VCARD\r\n",
+            adr_value
+        );
+        let parsed = parse_vcards(&input).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].street_address, street);
+    }
+
+    #[test]
+    fn parse_vcards_rejects_unmatched_end() {
+        assert!(parse_vcards("END:VCARD\r\n").is_err());
+    }
+}